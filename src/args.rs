@@ -0,0 +1,164 @@
+use crate::network_interface::NetworkInterface;
+use crate::vpn::{Firewall, Protocol, VpnProvider};
+use crate::websocket::WebSocketProxy;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "vopono",
+    about = "Run applications isolated in a VPN-routed network namespace"
+)]
+pub struct App {
+    #[structopt(subcommand)]
+    pub cmd: Command,
+
+    /// Enable debug logging
+    #[structopt(short, long)]
+    pub verbose: bool,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Run an application in a VPN network namespace
+    Exec(ExecCommand),
+    /// Initialise or re-sync all provider configs
+    Init,
+    /// List currently running vopono namespaces and applications
+    List(crate::list::ListCommand),
+    /// Sync the server list and configs for a given provider
+    Synch(SynchCommand),
+    /// Interactively write the default provider and server to the config file
+    /// (~/.config/vopono/config.toml); named profiles already in it are kept, not prompted for
+    SetDefaults,
+    /// Generate a shell completion script
+    Completions(CompletionsCommand),
+    /// Internal: runs the blocking WebSocket<->TCP forwarder loop. Not meant to be
+    /// invoked directly - `--websocket-proxy` launches this as a detached `ip netns
+    /// exec` child so the forwarder outlives the `vopono exec` process that started it.
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    WsForward(WsForwardCommand),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct WsForwardCommand {
+    /// Loopback port to accept local TCP connections on
+    pub local_port: u16,
+
+    /// Remote WebSocket proxy URL to relay them to
+    pub proxy: crate::websocket::WebSocketProxy,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct CompletionsCommand {
+    /// Shell to generate completions for
+    #[structopt(possible_values = &["bash", "zsh", "fish", "powershell", "elvish"])]
+    pub shell: structopt::clap::Shell,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct ExecCommand {
+    /// Named profile from the config file bundling provider/server/protocol/etc.
+    /// CLI flags below take precedence over the profile, which in turn takes
+    /// precedence over the config file's top-level defaults.
+    #[structopt(long)]
+    pub profile: Option<String>,
+
+    /// VPN provider to use
+    #[structopt(long = "provider")]
+    pub vpn_provider: Option<VpnProvider>,
+
+    /// VPN server alias or prefix (see serverlist.csv for the provider)
+    #[structopt(short, long)]
+    pub server: Option<String>,
+
+    /// VPN protocol to use, defaults to the provider's preferred protocol
+    #[structopt(long)]
+    pub protocol: Option<Protocol>,
+
+    /// Use a custom OpenVPN or WireGuard config file instead of a provider
+    #[structopt(long, parse(from_os_str))]
+    pub custom_config: Option<PathBuf>,
+
+    /// DNS servers to use inside the namespace, defaults to the provider's own
+    #[structopt(long)]
+    pub dns: Option<Vec<IpAddr>>,
+
+    /// Network interface to route traffic through, defaults to the active one
+    #[structopt(long)]
+    pub interface: Option<NetworkInterface>,
+
+    /// Disable the killswitch firewall rules (iptables or nftables)
+    #[structopt(long)]
+    pub no_killswitch: bool,
+
+    /// Tunnel the VPN connection through a local Shadowsocks client, e.g.
+    /// `--shadowsocks 1.2.3.4:8388` or `--shadowsocks mullvad` for a known bridge relay
+    #[structopt(long)]
+    pub shadowsocks: Option<String>,
+
+    /// Shadowsocks AEAD cipher, defaults to chacha20-ietf-poly1305
+    #[structopt(long)]
+    pub shadowsocks_cipher: Option<String>,
+
+    /// Shadowsocks password, required if --shadowsocks is set (and not using a known bridge)
+    #[structopt(long)]
+    pub shadowsocks_password: Option<String>,
+
+    /// Tunnel the VPN connection over a WebSocket proxy, e.g.
+    /// `--websocket-proxy ws://example.com/vopono`, for networks that block raw VPN
+    /// ports. A `wss://` URL is accepted, but its connections are not relayed: the
+    /// forwarder drops each one after the TLS and WebSocket handshakes
+    #[structopt(long)]
+    pub websocket_proxy: Option<WebSocketProxy>,
+
+    /// User to run the application as inside the namespace, defaults to $SUDO_USER
+    #[structopt(long)]
+    pub user: Option<String>,
+
+    /// Firewall backend to use for routing/killswitch rules, defaults to nftables if
+    /// the `nft` binary is available, else falls back to iptables
+    #[structopt(long)]
+    pub firewall: Option<Firewall>,
+
+    /// Script to run after the namespace/veth/routing is set up, before the VPN client
+    /// is launched. Can also be set via a profile's `hooks.pre_up` field.
+    #[structopt(long, parse(from_os_str))]
+    pub pre_up: Option<PathBuf>,
+
+    /// Script to run once the VPN client is confirmed running. Can also be set via a
+    /// profile's `hooks.post_up` field.
+    #[structopt(long, parse(from_os_str))]
+    pub post_up: Option<PathBuf>,
+
+    /// Script to run just before the namespace is torn down (last user exited). Can
+    /// also be set via a profile's `hooks.pre_down` field. Persisted alongside the
+    /// namespace so it still runs even when torn down by a later `vopono` invocation.
+    #[structopt(long, parse(from_os_str))]
+    pub pre_down: Option<PathBuf>,
+
+    /// Script to run just after the namespace is torn down. Can also be set via a
+    /// profile's `hooks.post_down` field.
+    #[structopt(long, parse(from_os_str))]
+    pub post_down: Option<PathBuf>,
+
+    /// Application (and arguments) to run inside the namespace
+    #[structopt(multiple = true)]
+    pub application: String,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct SynchCommand {
+    /// VPN provider to sync config for
+    #[structopt(long = "provider")]
+    pub vpn_provider: Option<VpnProvider>,
+
+    /// VPN protocol to sync config for
+    #[structopt(long)]
+    pub protocol: Option<Protocol>,
+
+    /// Only sync servers on this port
+    #[structopt(long)]
+    pub port: Option<u16>,
+}
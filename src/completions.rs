@@ -0,0 +1,39 @@
+use crate::args::App;
+use crate::vpn::{get_serverlist, VpnProvider};
+use log::debug;
+use std::io;
+use structopt::clap::Shell;
+use structopt::StructOpt;
+
+// Emits a completion script for the given shell to stdout, generated from the same
+// structopt/clap `App` definition used to parse real invocations - so providers,
+// protocols and subcommands always stay in sync with what `vopono exec` accepts.
+pub fn generate(shell: Shell) -> anyhow::Result<()> {
+    App::clap().gen_completions_to("vopono", shell, &mut io::stdout());
+    Ok(())
+}
+
+// Beyond clap's static completions (which only know the fixed `VpnProvider`/`Protocol`
+// variants), this enumerates server aliases from whatever serverlist.csv files have
+// already been synced, so users can see what `--server` prefixes are valid for
+// find_host_from_alias. Shells can't embed this into a static completion script, but
+// it is used for e.g. `vopono list --servers <provider>` style follow-up lookups.
+pub fn known_server_aliases(provider: &VpnProvider) -> Vec<String> {
+    match get_serverlist(provider) {
+        Ok(servers) => servers.into_iter().map(|s| s.alias).collect(),
+        Err(e) => {
+            debug!(
+                "No synced serverlist for {}, no dynamic completions available: {}",
+                provider, e
+            );
+            Vec::new()
+        }
+    }
+}
+
+pub fn known_provider_aliases() -> Vec<String> {
+    VpnProvider::variants()
+        .iter()
+        .map(|s| s.to_string())
+        .collect()
+}
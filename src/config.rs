@@ -0,0 +1,120 @@
+use crate::hooks::Hooks;
+use crate::util::config_dir;
+use crate::vpn::{Protocol, VpnProvider};
+use anyhow::Context;
+use dialoguer::Input;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+fn config_file_path() -> anyhow::Result<PathBuf> {
+    let mut path = config_dir()?;
+    path.push("vopono/config.toml");
+    Ok(path)
+}
+
+// A named bundle of exec settings, so e.g. `vopono exec --profile work firefox` does
+// not need to repeat `--provider`/`--server`/`--protocol` every time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profile {
+    pub provider: Option<VpnProvider>,
+    pub server: Option<String>,
+    pub protocol: Option<Protocol>,
+    pub dns: Option<Vec<IpAddr>>,
+    pub interface: Option<String>,
+    pub user: Option<String>,
+    pub killswitch: Option<bool>,
+    #[serde(default)]
+    pub hooks: Hooks,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Config {
+    pub default_provider: Option<VpnProvider>,
+    pub default_protocol: Option<Protocol>,
+    pub default_server: Option<String>,
+    #[serde(default)]
+    pub profile: HashMap<String, Profile>,
+}
+
+impl Config {
+    pub fn load() -> anyhow::Result<Self> {
+        let path = config_file_path()?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Self::load_from(&path)
+    }
+
+    fn load_from(path: &Path) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read config file: {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Could not parse config file: {}", path.display()))
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let path = config_file_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = toml::to_string_pretty(self)?;
+        fs::write(&path, contents)
+            .with_context(|| format!("Could not write config file: {}", path.display()))?;
+        Ok(())
+    }
+
+    // Resolves a profile by name, falling back to the top-level defaults for any field
+    // the profile itself does not set.
+    pub fn resolve_profile(&self, name: &str) -> anyhow::Result<Profile> {
+        let mut profile = self
+            .profile
+            .get(name)
+            .cloned()
+            .with_context(|| format!("No such profile: {}", name))?;
+        profile.provider = profile.provider.or_else(|| self.default_provider.clone());
+        profile.protocol = profile.protocol.or_else(|| self.default_protocol.clone());
+        profile.server = profile.server.or_else(|| self.default_server.clone());
+        Ok(profile)
+    }
+
+    pub fn defaults(&self) -> Profile {
+        Profile {
+            provider: self.default_provider.clone(),
+            protocol: self.default_protocol.clone(),
+            server: self.default_server.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+// Interactively writes ~/.config/vopono/config.toml, implementing `vopono set-defaults`.
+pub fn set_defaults() -> anyhow::Result<()> {
+    let mut config = Config::load().unwrap_or_default();
+
+    let provider_name: String = Input::<String>::new()
+        .with_prompt("Default VPN provider (leave blank to unset)")
+        .allow_empty(true)
+        .interact()?;
+    config.default_provider = if provider_name.is_empty() {
+        None
+    } else {
+        Some(
+            provider_name
+                .parse()
+                .map_err(|e| anyhow::anyhow!("{}", e))?,
+        )
+    };
+
+    let server: String = Input::<String>::new()
+        .with_prompt("Default server alias (leave blank to unset)")
+        .allow_empty(true)
+        .interact()?;
+    config.default_server = if server.is_empty() { None } else { Some(server) };
+
+    config.save()?;
+    log::info!("Wrote defaults to {}", config_file_path()?.display());
+    Ok(())
+}
@@ -0,0 +1,115 @@
+use crate::util::config_dir;
+use anyhow::Context;
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+// The four points in the namespace lifecycle a hook script can run at, named after
+// VPNCloud's hook feature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    PreUp,
+    PostUp,
+    PreDown,
+    PostDown,
+}
+
+impl HookPoint {
+    fn env_name(self) -> &'static str {
+        match self {
+            Self::PreUp => "VOPONO_HOOK_PRE_UP",
+            Self::PostUp => "VOPONO_HOOK_POST_UP",
+            Self::PreDown => "VOPONO_HOOK_PRE_DOWN",
+            Self::PostDown => "VOPONO_HOOK_POST_DOWN",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Hooks {
+    pub pre_up: Option<PathBuf>,
+    pub post_up: Option<PathBuf>,
+    pub pre_down: Option<PathBuf>,
+    pub post_down: Option<PathBuf>,
+}
+
+impl Hooks {
+    fn state_path(ns_name: &str) -> anyhow::Result<PathBuf> {
+        let mut path = config_dir()?;
+        path.push(format!("vopono/hooks/{}.toml", ns_name));
+        Ok(path)
+    }
+
+    // Persists the configured hook scripts for a namespace, so that `pre-down`/
+    // `post-down` can still be run later by whichever `vopono` invocation eventually
+    // tears the namespace down (e.g. `clean_dead_namespaces`, run from a fresh process
+    // that never saw the original `--pre-down`/`--post-down` flags).
+    pub fn save(&self, ns_name: &str) -> anyhow::Result<()> {
+        let path = Self::state_path(ns_name)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Could not write hook state: {}", path.display()))?;
+        Ok(())
+    }
+
+    // Loads the hook scripts previously saved for a namespace, defaulting to no hooks
+    // if none were ever saved (e.g. the namespace predates this feature).
+    pub fn load(ns_name: &str) -> anyhow::Result<Self> {
+        let path = Self::state_path(ns_name)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Could not read hook state: {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Could not parse hook state: {}", path.display()))
+    }
+
+    // Removes the persisted hook state once the namespace is gone for good.
+    pub fn remove_state(ns_name: &str) -> anyhow::Result<()> {
+        let path = Self::state_path(ns_name)?;
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    fn script_for(&self, point: HookPoint) -> Option<&PathBuf> {
+        match point {
+            HookPoint::PreUp => self.pre_up.as_ref(),
+            HookPoint::PostUp => self.post_up.as_ref(),
+            HookPoint::PreDown => self.pre_down.as_ref(),
+            HookPoint::PostDown => self.post_down.as_ref(),
+        }
+    }
+
+    // Runs the hook script for the given lifecycle point, if one was configured,
+    // exporting namespace context as environment variables so the script can e.g. add
+    // custom routes, mount namespace-scoped resolv.conf, or notify a monitoring system.
+    pub fn run(&self, point: HookPoint, context: &HashMap<&str, String>) -> anyhow::Result<()> {
+        let script = match self.script_for(point) {
+            Some(script) => script,
+            None => return Ok(()),
+        };
+
+        debug!("Running {} hook: {}", point.env_name(), script.display());
+        let status = Command::new(script)
+            .envs(context.iter().map(|(k, v)| (*k, v.clone())))
+            .status()
+            .with_context(|| format!("Failed to run hook script: {}", script.display()))?;
+
+        if !status.success() {
+            warn!(
+                "Hook script {} exited with status: {}",
+                script.display(),
+                status
+            );
+        }
+        Ok(())
+    }
+}
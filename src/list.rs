@@ -0,0 +1,56 @@
+use crate::completions::{known_provider_aliases, known_server_aliases};
+use crate::util::get_existing_namespaces;
+use crate::vpn::VpnProvider;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+pub struct ListCommand {
+    /// Print output as JSON
+    #[structopt(long)]
+    pub json: bool,
+
+    /// List known server aliases for a provider (from its synced serverlist.csv)
+    /// instead of listing running namespaces
+    #[structopt(long)]
+    pub servers: Option<VpnProvider>,
+
+    /// List known provider aliases instead of listing running namespaces
+    #[structopt(long)]
+    pub providers: bool,
+}
+
+pub fn output_list(command: ListCommand) -> anyhow::Result<()> {
+    if command.providers {
+        let aliases = known_provider_aliases();
+        if command.json {
+            println!("{}", serde_json::to_string(&aliases)?);
+        } else {
+            for alias in aliases {
+                println!("{}", alias);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(provider) = &command.servers {
+        let aliases = known_server_aliases(provider);
+        if command.json {
+            println!("{}", serde_json::to_string(&aliases)?);
+        } else {
+            for alias in aliases {
+                println!("{}", alias);
+            }
+        }
+        return Ok(());
+    }
+
+    let namespaces = get_existing_namespaces()?;
+    if command.json {
+        println!("{}", serde_json::to_string(&namespaces)?);
+    } else {
+        for ns in namespaces {
+            println!("{}", ns);
+        }
+    }
+    Ok(())
+}
@@ -1,34 +1,48 @@
 mod application_wrapper;
 mod args;
+mod completions;
+mod config;
 mod dns_config;
+mod hooks;
 mod iptables;
 mod list;
 mod netns;
 mod network_interface;
+mod nftables;
 mod openvpn;
+mod protonvpn;
+mod shadowsocks;
 mod sync;
 mod sysctl;
 mod util;
 mod veth_pair;
 mod vpn;
+mod websocket;
 mod wireguard;
 
-use anyhow::{anyhow, bail};
+use anyhow::{anyhow, bail, Context};
 use application_wrapper::ApplicationWrapper;
 use args::{ExecCommand, SynchCommand};
+use config::Config;
+use hooks::{HookPoint, Hooks};
 use list::output_list;
 use log::{debug, error, info, LevelFilter};
 use netns::NetworkNamespace;
 use network_interface::{get_active_interfaces, NetworkInterface};
+use shadowsocks::{kill_local as kill_shadowsocks, run_local, ShadowsocksConfig};
+use std::collections::HashMap;
 use std::io::{self, Write};
 use structopt::StructOpt;
-use sync::synch;
+use sync::{synch, synch_if_missing};
 use sysctl::SysCtl;
-use util::clean_dead_namespaces;
-use util::{clean_dead_locks, get_existing_namespaces, get_target_subnet};
-use util::{config_dir, elevate_privileges};
+use util::{clean_dead_locks, get_dead_namespaces, get_existing_namespaces, get_target_subnet};
+use util::{config_dir, elevate_privileges, teardown_namespace};
 use vpn::VpnProvider;
-use vpn::{get_auth, get_protocol, Protocol};
+use vpn::{find_host_from_alias, get_auth, get_protocol, get_serverlist, Firewall, Protocol};
+use websocket::{
+    kill_forwarder as kill_websocket_forwarder, run_forwarder as run_websocket_forwarder,
+    spawn_forwarder as spawn_websocket_forwarder, WebSocketProxy,
+};
 use wireguard::get_config_from_alias;
 
 // TODO:
@@ -37,8 +51,6 @@ use wireguard::get_config_from_alias;
 // - Disable ipv6 traffic when not routed?
 // - Test configuration for wireless interface for OpenVPN
 // - Allow for not saving OpenVPN creds to config
-// - Allow for choice between iptables and nftables and avoid mixed dependency
-// - Mullvad Shadowsocks
 // - Handle setting and using default provider and server
 
 fn main() -> anyhow::Result<()> {
@@ -60,7 +72,7 @@ fn main() -> anyhow::Result<()> {
             clean_dead_locks()?;
 
             elevate_privileges()?;
-            clean_dead_namespaces()?;
+            teardown_dead_namespaces()?;
             exec(cmd)?
         }
         args::Command::Init => {
@@ -78,14 +90,130 @@ fn main() -> anyhow::Result<()> {
         args::Command::Synch(synchcmd) => {
             elevate_privileges()?;
             synch(synchcmd)?;
-        } // args::Command::SetDefaults(cmd) => todo!(),
+        }
+        args::Command::SetDefaults => {
+            config::set_defaults()?;
+        }
+        args::Command::Completions(cmd) => {
+            completions::generate(cmd.shell)?;
+        }
+        args::Command::WsForward(cmd) => {
+            run_websocket_forwarder(cmd.local_port, &cmd.proxy)?;
+        }
+    }
+    Ok(())
+}
+
+// Tears down any namespace whose lockfile count has dropped to zero, running its
+// persisted pre-down/post-down hooks (saved by `exec` via `Hooks::save`) either side of
+// the actual teardown, since the `vopono` invocation doing the cleanup is very often not
+// the one that originally set up the namespace. One namespace's teardown failing (e.g. a
+// missing pre-down script) must not stop the others from being cleaned up, nor the exec
+// command that triggered this sweep from running.
+fn teardown_dead_namespaces() -> anyhow::Result<()> {
+    for ns_name in get_dead_namespaces()? {
+        if let Err(e) = teardown_dead_namespace(&ns_name) {
+            error!("Failed to tear down dead namespace {}: {}", ns_name, e);
+        }
     }
     Ok(())
 }
 
+fn teardown_dead_namespace(ns_name: &str) -> anyhow::Result<()> {
+    let hooks = Hooks::load(ns_name)?;
+    let mut context = HashMap::new();
+    context.insert("VOPONO_NS", ns_name.to_string());
+
+    hooks.run(HookPoint::PreDown, &context)?;
+    teardown_namespace(ns_name)?;
+    // No-op if this namespace used iptables rather than nftables.
+    nftables::flush_nftables_rule(ns_name)?;
+    // No-op if this namespace never used --shadowsocks/--websocket-proxy.
+    kill_shadowsocks(ns_name)?;
+    kill_websocket_forwarder(ns_name)?;
+    hooks.run(HookPoint::PostDown, &context)?;
+    Hooks::remove_state(ns_name)?;
+    Ok(())
+}
+
+// Dispatches to the chosen firewall backend's routing/killswitch rules. Kept as a free
+// function (rather than inlined per-protocol) so both the OpenVPN and WireGuard
+// branches of `exec` set up the same rules the same way.
+//
+// The two backends divide killswitch responsibility differently: nftables.rs is fully
+// self-contained (this series' own addition), so `add_nftables_rule` takes the VPN
+// tunnel interface plus the optional Shadowsocks/WebSocket egress targets and sets up
+// the whole forward chain itself. `add_iptables_rule` predates this series and only
+// ever set up subnet routing; the iptables killswitch/allow-rules it doesn't cover are
+// applied by `run_openvpn`/`run_wireguard` themselves, the same way `!no_killswitch`
+// already worked before nftables support existed.
+#[allow(clippy::too_many_arguments)]
+fn setup_firewall(
+    ns: &mut NetworkNamespace,
+    ns_name: &str,
+    target_subnet: ipnetwork::Ipv4Network,
+    interface: NetworkInterface,
+    vpn_interface: &str,
+    shadowsocks_config: Option<&ShadowsocksConfig>,
+    websocket_proxy: Option<&WebSocketProxy>,
+    firewall: Firewall,
+    killswitch: bool,
+) -> anyhow::Result<()> {
+    match firewall {
+        Firewall::NfTables => nftables::add_nftables_rule(
+            ns_name,
+            target_subnet,
+            &interface.name,
+            vpn_interface,
+            killswitch,
+            shadowsocks_config,
+            websocket_proxy,
+        ),
+        Firewall::IpTables => ns.add_iptables_rule(target_subnet, interface),
+    }
+}
+
+// Reads the real VPN endpoint out of a WireGuard config's `Endpoint = host:port` line,
+// for Shadowsocks to forward to - WireGuard has no serverlist.csv lookup like OpenVPN,
+// so the config file itself is the only source for it.
+fn wireguard_endpoint(config_path: &std::path::Path) -> anyhow::Result<(String, u16)> {
+    let contents = std::fs::read_to_string(config_path)
+        .with_context(|| format!("Could not read WireGuard config: {}", config_path.display()))?;
+    let line = contents
+        .lines()
+        .find(|l| l.trim_start().to_lowercase().starts_with("endpoint"))
+        .ok_or_else(|| {
+            anyhow!(
+                "No Endpoint line found in WireGuard config: {}",
+                config_path.display()
+            )
+        })?;
+    let value = line
+        .split('=')
+        .nth(1)
+        .ok_or_else(|| anyhow!("Malformed Endpoint line: {}", line))?
+        .trim();
+    let (host, port) = value
+        .rsplit_once(':')
+        .ok_or_else(|| anyhow!("Endpoint line missing port: {}", value))?;
+    let port: u16 = port
+        .parse()
+        .with_context(|| format!("Invalid Endpoint port: {}", port))?;
+    Ok((host.to_string(), port))
+}
+
 fn exec(command: ExecCommand) -> anyhow::Result<()> {
     let provider: VpnProvider;
     let server_name: String;
+    let mut protocol_override = command.protocol.clone();
+
+    // CLI flags take precedence over a named profile, which takes precedence over the
+    // config file's top-level defaults.
+    let config = Config::load().unwrap_or_default();
+    let profile = match &command.profile {
+        Some(name) => Some(config.resolve_profile(name)?),
+        None => Some(config.defaults()),
+    };
 
     // TODO: Clean this up and merge with protocol logic below
     if let Some(path) = &command.custom_config {
@@ -107,16 +235,27 @@ fn exec(command: ExecCommand) -> anyhow::Result<()> {
                 .collect::<String>()[0..4],
         );
     } else {
-        // Get server and provider
-        // TODO: Handle default case and remove expect()
-        provider = command.vpn_provider.expect("Enter a VPN provider");
+        // Get server and provider, falling back to the resolved profile/defaults
+        provider = command
+            .vpn_provider
+            .or_else(|| profile.as_ref().and_then(|p| p.provider.clone()))
+            .ok_or_else(|| {
+                anyhow!("No VPN provider given, and none set via --profile or `vopono set-defaults`")
+            })?;
         if provider == VpnProvider::Custom {
             bail!("Must provide config file if using custom VPN Provider");
         }
-        server_name = command.server.expect("Enter a VPN server prefix");
+        server_name = command
+            .server
+            .or_else(|| profile.as_ref().and_then(|p| p.server.clone()))
+            .ok_or_else(|| {
+                anyhow!("No VPN server given, and none set via --profile or `vopono set-defaults`")
+            })?;
+        protocol_override =
+            protocol_override.or_else(|| profile.as_ref().and_then(|p| p.protocol.clone()));
     }
     // Check protocol is valid for provider
-    let protocol = get_protocol(&provider, command.protocol)?;
+    let protocol = get_protocol(&provider, protocol_override)?;
     // Check config files exist for provider
     if provider != VpnProvider::Custom {
         let mut cdir = config_dir()?;
@@ -131,7 +270,7 @@ fn exec(command: ExecCommand) -> anyhow::Result<()> {
                 "Config files for {} {} do not exist, running vopono sync",
                 provider, protocol
             );
-            synch(SynchCommand {
+            synch_if_missing(SynchCommand {
                 vpn_provider: Some(provider.clone()),
                 protocol: Some(protocol.clone()),
                 port: None,
@@ -141,17 +280,28 @@ fn exec(command: ExecCommand) -> anyhow::Result<()> {
 
     let ns_name = format!("vopono_{}_{}", provider.alias(), server_name);
 
+    // CLI killswitch opt-out takes precedence, then the profile's `killswitch` field,
+    // defaulting to enabled.
+    let killswitch_enabled = if command.no_killswitch {
+        false
+    } else {
+        profile.as_ref().and_then(|p| p.killswitch).unwrap_or(true)
+    };
+
     let mut ns;
     let _sysctl;
-    let interface: NetworkInterface = match command.interface {
-        Some(x) => anyhow::Result::<NetworkInterface>::Ok(x),
-        None => Ok(NetworkInterface::new(
+    let interface: NetworkInterface = if let Some(interface) = command.interface {
+        interface
+    } else if let Some(name) = profile.as_ref().and_then(|p| p.interface.clone()) {
+        NetworkInterface::new(name)?
+    } else {
+        NetworkInterface::new(
             get_active_interfaces()?
                 .into_iter()
                 .next()
                 .ok_or_else(|| anyhow!("No active network interface"))?,
-        )?),
-    }?;
+        )?
+    };
 
     debug!("Interface: {}", &interface.name);
     // Better to check for lockfile exists?
@@ -159,8 +309,36 @@ fn exec(command: ExecCommand) -> anyhow::Result<()> {
         // If namespace exists, read its lock config
         ns = NetworkNamespace::from_existing(ns_name)?;
     } else {
+        let firewall = Firewall::get_firewall(command.firewall);
+        debug!("Using firewall backend: {}", firewall);
         ns = NetworkNamespace::new(ns_name.clone(), provider.clone(), protocol.clone())?;
         let target_subnet = get_target_subnet()?;
+
+        if command.shadowsocks.is_some() && command.websocket_proxy.is_some() {
+            bail!("--shadowsocks and --websocket-proxy cannot be used together");
+        }
+        if protocol == Protocol::Wireguard && command.websocket_proxy.is_some() {
+            bail!("--websocket-proxy is only supported with --protocol openvpn");
+        }
+
+        let profile_hooks = profile.as_ref().map(|p| p.hooks.clone()).unwrap_or_default();
+        let hooks = Hooks {
+            pre_up: command.pre_up.clone().or(profile_hooks.pre_up),
+            post_up: command.post_up.clone().or(profile_hooks.post_up),
+            pre_down: command.pre_down.clone().or(profile_hooks.pre_down),
+            post_down: command.post_down.clone().or(profile_hooks.post_down),
+        };
+        // Persist the hook scripts against the namespace so pre-down/post-down still
+        // run later, even from a `vopono` invocation that never saw these flags.
+        hooks.save(&ns_name)?;
+
+        let mut hook_context: HashMap<&str, String> = HashMap::new();
+        hook_context.insert("VOPONO_NS", ns_name.clone());
+        hook_context.insert("VOPONO_PROVIDER", provider.alias());
+        hook_context.insert("VOPONO_PROTOCOL", protocol.to_string());
+        hook_context.insert("VOPONO_VETH", ns.veth_pair_name());
+        hooks.run(HookPoint::PreUp, &hook_context)?;
+
         match protocol {
             Protocol::OpenVpn => {
                 if command.custom_config.is_none() {
@@ -170,16 +348,71 @@ fn exec(command: ExecCommand) -> anyhow::Result<()> {
                 ns.add_loopback()?;
                 ns.add_veth_pair()?;
                 ns.add_routing(target_subnet)?;
-                ns.add_iptables_rule(target_subnet, interface)?;
+
+                // Shadowsocks needs to be told the real VPN endpoint to forward to (see
+                // `ShadowsocksConfig`'s doc comment) - for a provider server that's
+                // wherever its own serverlist.csv says; a custom config has no such
+                // lookup, so it's rejected rather than silently forwarding nowhere.
+                let shadowsocks_config = command
+                    .shadowsocks
+                    .as_ref()
+                    .map(|target| -> anyhow::Result<ShadowsocksConfig> {
+                        if command.custom_config.is_some() {
+                            bail!(
+                                "--shadowsocks cannot be combined with --custom-config: \
+                                 the real VPN endpoint can only be looked up from a provider's serverlist"
+                            );
+                        }
+                        let serverlist = get_serverlist(&provider)?;
+                        let (host, port, _, _) = find_host_from_alias(&server_name, &serverlist)?;
+                        ShadowsocksConfig::new(
+                            target,
+                            command.shadowsocks_cipher.clone(),
+                            command.shadowsocks_password.clone(),
+                            &provider,
+                            host,
+                            port,
+                        )
+                    })
+                    .transpose()?;
+
+                setup_firewall(
+                    &mut ns,
+                    &ns_name,
+                    target_subnet,
+                    interface,
+                    "tun0",
+                    shadowsocks_config.as_ref(),
+                    command.websocket_proxy.as_ref(),
+                    firewall,
+                    killswitch_enabled,
+                )?;
                 _sysctl = SysCtl::enable_ipv4_forwarding();
-                let dns = command.dns.unwrap_or(provider.dns()?);
+                let dns = command
+                    .dns
+                    .clone()
+                    .or_else(|| profile.as_ref().and_then(|p| p.dns.clone()))
+                    .unwrap_or(provider.dns()?);
                 ns.dns_config(&dns)?;
+
+                let shadowsocks_local_port = shadowsocks_config
+                    .as_ref()
+                    .map(|cfg| run_local(&ns_name, cfg, false))
+                    .transpose()?
+                    .map(|(_, port)| port);
+                let websocket_local_port = command
+                    .websocket_proxy
+                    .as_ref()
+                    .map(|proxy| spawn_websocket_forwarder(&ns_name, proxy))
+                    .transpose()?;
+
                 ns.run_openvpn(
                     &provider,
                     &server_name,
                     command.custom_config,
                     &dns,
-                    !command.no_killswitch,
+                    killswitch_enabled,
+                    shadowsocks_local_port.or(websocket_local_port),
                 )?;
                 debug!(
                     "Checking that OpenVPN is running in namespace: {}",
@@ -194,6 +427,15 @@ fn exec(command: ExecCommand) -> anyhow::Result<()> {
             "OpenVPN not running in network namespace, probable dead lock file authentication error"
         ));
                 }
+                let mut post_up_context = hook_context.clone();
+                post_up_context.insert(
+                    "VOPONO_DNS",
+                    dns.iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<String>>()
+                        .join(","),
+                );
+                hooks.run(HookPoint::PostUp, &post_up_context)?;
             }
             Protocol::Wireguard => {
                 let config = if command.custom_config.is_some() {
@@ -204,9 +446,49 @@ fn exec(command: ExecCommand) -> anyhow::Result<()> {
                 ns.add_loopback()?;
                 ns.add_veth_pair()?;
                 ns.add_routing(target_subnet)?;
-                ns.add_iptables_rule(target_subnet, interface)?;
+
+                // WireGuard has no serverlist lookup like OpenVPN - the config file's
+                // own `Endpoint = host:port` line is the only source for the real VPN
+                // endpoint Shadowsocks needs to forward to.
+                let shadowsocks_config = command
+                    .shadowsocks
+                    .as_ref()
+                    .map(|target| -> anyhow::Result<ShadowsocksConfig> {
+                        let (host, port) = wireguard_endpoint(&config)?;
+                        ShadowsocksConfig::new(
+                            target,
+                            command.shadowsocks_cipher.clone(),
+                            command.shadowsocks_password.clone(),
+                            &provider,
+                            host,
+                            port,
+                        )
+                    })
+                    .transpose()?;
+
+                setup_firewall(
+                    &mut ns,
+                    &ns_name,
+                    target_subnet,
+                    interface,
+                    "wg0",
+                    shadowsocks_config.as_ref(),
+                    None,
+                    firewall,
+                    killswitch_enabled,
+                )?;
                 _sysctl = SysCtl::enable_ipv4_forwarding();
-                ns.run_wireguard(config, !command.no_killswitch)?;
+
+                // `true`: only Shadowsocks' UDP relay mode (not its TCP tunnel mode)
+                // can carry WireGuard's UDP datagrams.
+                let shadowsocks_local_port = shadowsocks_config
+                    .as_ref()
+                    .map(|cfg| run_local(&ns_name, cfg, true))
+                    .transpose()?
+                    .map(|(_, port)| port);
+
+                ns.run_wireguard(config, killswitch_enabled, shadowsocks_local_port)?;
+                hooks.run(HookPoint::PostUp, &hook_context)?;
             }
         }
     }
@@ -215,12 +497,11 @@ fn exec(command: ExecCommand) -> anyhow::Result<()> {
     let group = util::get_group(&username)?;
     let ns = ns.write_lockfile(&command.application, &username, &group)?;
 
-    // User for application command, if None will use root
-    let user = if command.user.is_none() {
-        std::env::var("SUDO_USER").ok()
-    } else {
-        command.user
-    };
+    // User for application command: CLI flag, then profile, then $SUDO_USER, else root
+    let user = command
+        .user
+        .or_else(|| profile.as_ref().and_then(|p| p.user.clone()))
+        .or_else(|| std::env::var("SUDO_USER").ok());
 
     let application = ApplicationWrapper::new(&ns, &command.application, user)?;
     let output = application.wait_with_output()?;
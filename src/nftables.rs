@@ -0,0 +1,100 @@
+use crate::shadowsocks::ShadowsocksConfig;
+use crate::websocket::WebSocketProxy;
+use anyhow::Context;
+use log::debug;
+use std::net::ToSocketAddrs;
+use std::process::Command;
+
+// Mirrors iptables::add_iptables_rule but targets a dedicated `inet` table scoped to
+// the namespace, so enabling/disabling the killswitch never touches the host's own
+// iptables/nftables rules.
+fn table_name(ns_name: &str) -> String {
+    format!("vopono_{}", ns_name)
+}
+
+fn run_nft(args: &[&str]) -> anyhow::Result<()> {
+    debug!("nft {}", args.join(" "));
+    let status = Command::new("nft")
+        .args(args)
+        .status()
+        .with_context(|| format!("Failed to run: nft {}", args.join(" ")))?;
+    if !status.success() {
+        anyhow::bail!("nft {} failed with status: {}", args.join(" "), status);
+    }
+    Ok(())
+}
+
+// Resolves a host:port pair (which may be a hostname, e.g. a --websocket-proxy URL) to
+// the IP nftables should allow egress to.
+fn resolve_allow_target(host: &str, port: u16) -> anyhow::Result<String> {
+    let addr = (host, port)
+        .to_socket_addrs()
+        .with_context(|| format!("Could not resolve {}:{}", host, port))?
+        .next()
+        .with_context(|| format!("No addresses found for {}:{}", host, port))?;
+    Ok(addr.ip().to_string())
+}
+
+// Creates the `inet vopono_<ns_name>` table and its forward chain, with rules for the
+// veth subnet routing and a default-drop killswitch that only allows the VPN tunnel
+// interface itself (`vpn_interface`, e.g. `tun0`/`wg0` - named after the fixed interface
+// vopono brings up inside the namespace) plus, optionally, the namespace's Shadowsocks
+// relay or WebSocket proxy as egress.
+pub fn add_nftables_rule(
+    ns_name: &str,
+    target_subnet: ipnetwork::Ipv4Network,
+    out_interface: &str,
+    vpn_interface: &str,
+    killswitch: bool,
+    shadowsocks: Option<&ShadowsocksConfig>,
+    websocket_proxy: Option<&WebSocketProxy>,
+) -> anyhow::Result<()> {
+    let table = table_name(ns_name);
+
+    run_nft(&["add", "table", "inet", &table])?;
+    run_nft(&[
+        "add", "chain", "inet", &table, "forward",
+        "{", "type", "filter", "hook", "forward", "priority", "0", ";", "policy", "drop", ";", "}",
+    ])?;
+    run_nft(&[
+        "add", "rule", "inet", &table, "forward", "ip", "saddr", &target_subnet.to_string(),
+        "oifname", out_interface, "accept",
+    ])?;
+
+    if killswitch {
+        run_nft(&[
+            "add", "rule", "inet", &table, "forward", "oifname", vpn_interface, "accept",
+        ])?;
+        if let Some(ss) = shadowsocks {
+            let ip = resolve_allow_target(&ss.remote_host, ss.remote_port)?;
+            run_nft(&[
+                "add", "rule", "inet", &table, "forward", "ip", "daddr", &ip,
+                "tcp", "dport", &ss.remote_port.to_string(), "accept",
+            ])?;
+        }
+        if let Some(proxy) = websocket_proxy {
+            let host = proxy.host()?;
+            let port = proxy.port();
+            let ip = resolve_allow_target(&host, port)?;
+            run_nft(&[
+                "add", "rule", "inet", &table, "forward", "ip", "daddr", &ip,
+                "tcp", "dport", &port.to_string(), "accept",
+            ])?;
+        }
+    }
+
+    Ok(())
+}
+
+// Removes the namespace's nftables table entirely; only this table is touched, the
+// host's own nftables/iptables rules are left alone.
+pub fn flush_nftables_rule(ns_name: &str) -> anyhow::Result<()> {
+    let table = table_name(ns_name);
+    // Already gone (e.g. namespace never fully came up) - nothing to flush.
+    let _ = run_nft(&["delete", "table", "inet", &table]);
+    Ok(())
+}
+
+pub fn is_available() -> bool {
+    which::which("nft").is_ok()
+}
@@ -0,0 +1,181 @@
+use crate::util::config_dir;
+use crate::vpn::VpnServer;
+use anyhow::Context;
+use dialoguer::{Confirm, MultiSelect};
+use log::{debug, info, warn};
+use serde::Deserialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// https://api.protonvpn.ch/vpn/logicals - unauthenticated endpoint listing all logical
+// servers, their load and supported features.
+const LOGICALS_URL: &str = "https://api.protonvpn.ch/vpn/logicals";
+
+// Bit flags as returned in the `Features` field of each logical server.
+const FEATURE_SECURE_CORE: u32 = 1;
+const FEATURE_TOR: u32 = 2;
+const FEATURE_P2P: u32 = 4;
+
+#[derive(Debug, Deserialize)]
+struct LogicalsResponse {
+    #[serde(rename = "LogicalServers")]
+    logical_servers: Vec<LogicalServer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogicalServer {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "EntryCountry")]
+    entry_country: String,
+    #[serde(rename = "ExitCountry")]
+    exit_country: String,
+    #[serde(rename = "Load")]
+    load: f32,
+    #[serde(rename = "Tier")]
+    tier: u8,
+    #[serde(rename = "Features")]
+    features: u32,
+    #[serde(rename = "Servers")]
+    servers: Vec<PhysicalServer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PhysicalServer {
+    #[serde(rename = "EntryIP")]
+    entry_ip: String,
+    #[serde(rename = "X25519PublicKey")]
+    wireguard_public_key: Option<String>,
+}
+
+// Connection options that ProtonVPN encodes as suffixes on the OpenVPN username, see
+// https://protonvpn.com/support/vpn-accelerator-naming-scheme/
+fn suffix_options() -> &'static [(&'static str, &'static str)] {
+    &[
+        ("+b", "NetShield ad/malware blocking"),
+        ("+f2", "NetShield ad/malware/tracker blocking"),
+        ("+nr", "Moderate NAT"),
+        ("+pmp", "Port forwarding (P2P)"),
+    ]
+}
+
+// Prompts the user for which ProtonVPN OpenVPN username suffixes to enable and returns
+// the concatenated suffix string to append to the username (may be empty).
+pub fn protonvpn_suffix() -> anyhow::Result<String> {
+    let options = suffix_options();
+    let labels: Vec<&str> = options.iter().map(|(_, label)| *label).collect();
+    let chosen = MultiSelect::new()
+        .with_prompt("Select ProtonVPN connection options (space to toggle, enter to confirm)")
+        .items(&labels)
+        .interact()?;
+
+    Ok(chosen
+        .into_iter()
+        .map(|i| options[i].0)
+        .collect::<Vec<&str>>()
+        .join(""))
+}
+
+fn serverlist_path(provider_dir: &Path) -> PathBuf {
+    provider_dir.join("openvpn/serverlist.csv")
+}
+
+pub fn has_synced_wireguard_configs() -> anyhow::Result<bool> {
+    let mut dir = config_dir()?;
+    dir.push("vopono/pvpn/wireguard");
+    Ok(dir.exists() && dir.read_dir()?.next().is_some())
+}
+
+// Fetches the ProtonVPN logical server list, filters to servers available to the
+// user's tier, and writes serverlist.csv (for OpenVPN) plus per-server WireGuard
+// configs, mirroring how the other providers lay out their config directories.
+pub fn synch(provider_dir: &Path) -> anyhow::Result<()> {
+    info!("Fetching ProtonVPN server list");
+    let resp: LogicalsResponse = ureq::get(LOGICALS_URL)
+        .call()
+        .with_context(|| "Could not fetch ProtonVPN logical servers")?
+        .into_json()
+        .with_context(|| "Could not parse ProtonVPN logical servers response")?;
+
+    let max_tier = if Confirm::new()
+        .with_prompt("Do you have a ProtonVPN Plus (or higher) subscription?")
+        .default(false)
+        .interact()?
+    {
+        2
+    } else {
+        0
+    };
+
+    let want_secure_core = Confirm::new()
+        .with_prompt("Include Secure Core servers?")
+        .default(false)
+        .interact()?;
+
+    let openvpn_dir = provider_dir.join("openvpn");
+    let wireguard_dir = provider_dir.join("wireguard");
+    std::fs::create_dir_all(&openvpn_dir)?;
+    std::fs::create_dir_all(&wireguard_dir)?;
+
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_path(serverlist_path(provider_dir))?;
+
+    let mut written = 0;
+    for server in resp.logical_servers {
+        if server.tier > max_tier {
+            continue;
+        }
+        if server.features & FEATURE_SECURE_CORE != 0 && !want_secure_core {
+            continue;
+        }
+
+        let alias = server.name.to_lowercase().replace('#', "-");
+        debug!(
+            "Server {} ({} -> {}), load {}%, tor={}, p2p={}",
+            server.name,
+            server.entry_country,
+            server.exit_country,
+            server.load,
+            server.features & FEATURE_TOR != 0,
+            server.features & FEATURE_P2P != 0,
+        );
+
+        let physical = match server.servers.first() {
+            Some(p) => p,
+            None => continue,
+        };
+
+        csv_writer.serialize(VpnServer {
+            name: server.name.clone(),
+            alias: alias.clone(),
+            host: physical.entry_ip.clone(),
+            port: Some(1194),
+            protocol: None,
+        })?;
+
+        if let Some(public_key) = &physical.wireguard_public_key {
+            let mut config_path = wireguard_dir.clone();
+            config_path.push(format!("{}.conf", alias));
+            let mut f = std::fs::File::create(&config_path)?;
+            // Private key and address are filled in separately once the user has
+            // registered a WireGuard key with their ProtonVPN account.
+            write!(
+                f,
+                "[Interface]\nPrivateKey = REPLACE_ME\nAddress = 10.2.0.2/32\n\n[Peer]\nPublicKey = {}\nEndpoint = {}:51820\nAllowedIPs = 0.0.0.0/0\n",
+                public_key, physical.entry_ip
+            )?;
+        }
+
+        written += 1;
+    }
+    csv_writer.flush()?;
+
+    if written == 0 {
+        warn!("No ProtonVPN servers matched the chosen tier/filters");
+    } else {
+        info!("Wrote {} ProtonVPN servers to serverlist.csv", written);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use crate::util::config_dir;
+use crate::vpn::VpnProvider;
+use anyhow::{anyhow, bail, Context};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::fs::File;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+
+// Fallback Mullvad Shadowsocks bridge relays, used when `vopono sync --provider mullvad`
+// has never been run (so shadowsocks_bridges.csv does not exist yet) or the fetch below
+// failed. Mullvad publishes the up to date list alongside their regular OpenVPN/WireGuard
+// relays; `sync_mullvad_bridges` refreshes `shadowsocks_bridges.csv` from that list.
+const MULLVAD_BRIDGES: &[(&str, u16)] = &[
+    ("185.213.154.68", 443),
+    ("193.138.218.78", 443),
+];
+
+// Mullvad's Shadowsocks bridges all share this fixed obfuscation password (the same
+// constant their own apps use) - only the relay address varies, so `--shadowsocks
+// mullvad` does not need `--shadowsocks-password`.
+const MULLVAD_BRIDGE_PASSWORD: &str = "mullvad";
+
+const MULLVAD_RELAYS_URL: &str = "https://api.mullvad.net/www/relays/all/";
+
+#[derive(Debug, Deserialize)]
+struct MullvadRelay {
+    hostname: String,
+    ipv4_addr_in: String,
+    #[serde(rename = "type")]
+    relay_type: String,
+}
+
+fn bridges_path(provider_dir: &Path) -> std::path::PathBuf {
+    provider_dir.join("shadowsocks_bridges.csv")
+}
+
+// Mirrors the shape (and the has_headers(false)/csv-crate convention) of
+// vpn::VpnServer's serverlist.csv - same kind of data, same on-disk format.
+#[derive(Debug, Serialize, Deserialize)]
+struct BridgeRecord {
+    host: String,
+    port: u16,
+}
+
+// Fetches Mullvad's current relay list and writes out the bridge relays (port 443) to
+// `shadowsocks_bridges.csv` in the provider's config dir, so `--shadowsocks mullvad`
+// picks up fresh relays instead of the hardcoded fallback.
+pub fn sync_mullvad_bridges(provider_dir: &Path) -> anyhow::Result<()> {
+    let relays: Vec<MullvadRelay> = ureq::get(MULLVAD_RELAYS_URL)
+        .call()
+        .with_context(|| format!("Could not fetch Mullvad relay list: {}", MULLVAD_RELAYS_URL))?
+        .into_json()
+        .with_context(|| "Could not parse Mullvad relay list")?;
+
+    let bridges: Vec<BridgeRecord> = relays
+        .into_iter()
+        .filter(|r| r.relay_type == "bridge")
+        .map(|r| {
+            debug!("Found Mullvad bridge relay: {}", r.hostname);
+            BridgeRecord {
+                host: r.ipv4_addr_in,
+                port: 443,
+            }
+        })
+        .collect();
+
+    if bridges.is_empty() {
+        warn!("Mullvad relay list contained no bridge relays, keeping existing shadowsocks_bridges.csv");
+        return Ok(());
+    }
+
+    let path = bridges_path(provider_dir);
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_path(&path)
+        .with_context(|| format!("Could not write {}", path.display()))?;
+    for bridge in &bridges {
+        wtr.serialize(bridge)?;
+    }
+    wtr.flush()?;
+    info!("Synced {} Mullvad Shadowsocks bridge relays", bridges.len());
+    Ok(())
+}
+
+// Reads the bridge relays synced by `sync_mullvad_bridges`, falling back to the
+// hardcoded list if `vopono sync --provider mullvad` has never been run.
+fn known_mullvad_bridges() -> Vec<(String, u16)> {
+    let path = match config_dir() {
+        Ok(dir) => bridges_path(&dir.join("vopono/mv")),
+        Err(_) => return default_mullvad_bridges(),
+    };
+    let file = match File::open(&path) {
+        Ok(file) => file,
+        Err(_) => return default_mullvad_bridges(),
+    };
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(file);
+    let bridges: Vec<(String, u16)> = rdr
+        .deserialize::<BridgeRecord>()
+        .filter_map(|row| row.ok())
+        .map(|r| (r.host, r.port))
+        .collect();
+    if bridges.is_empty() {
+        default_mullvad_bridges()
+    } else {
+        bridges
+    }
+}
+
+fn default_mullvad_bridges() -> Vec<(String, u16)> {
+    MULLVAD_BRIDGES
+        .iter()
+        .map(|(host, port)| (host.to_string(), *port))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct ShadowsocksConfig {
+    pub remote_host: String,
+    pub remote_port: u16,
+    pub cipher: String,
+    pub password: String,
+    // The real VPN endpoint OpenVPN/WireGuard would otherwise dial directly; sslocal is
+    // run in `tunnel` mode so it forwards decrypted traffic straight here instead of
+    // relying on a kernel REDIRECT rule.
+    pub forward_host: String,
+    pub forward_port: u16,
+}
+
+impl ShadowsocksConfig {
+    // `target` is either a literal `host:port` or a provider shorthand such as
+    // `mullvad`, which resolves to one of its known bridge relays.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        target: &str,
+        cipher: Option<String>,
+        password: Option<String>,
+        provider: &VpnProvider,
+        forward_host: String,
+        forward_port: u16,
+    ) -> anyhow::Result<Self> {
+        let (remote_host, remote_port, password) = if target.eq_ignore_ascii_case("mullvad") {
+            if *provider != VpnProvider::Mullvad {
+                bail!("--shadowsocks mullvad can only be used with --provider mullvad");
+            }
+            let (host, port) = known_mullvad_bridges()
+                .into_iter()
+                .next()
+                .ok_or_else(|| anyhow!("No known Mullvad Shadowsocks bridges"))?;
+            (host, port, password.unwrap_or_else(|| MULLVAD_BRIDGE_PASSWORD.to_string()))
+        } else {
+            let addr: SocketAddr = target
+                .parse()
+                .with_context(|| format!("Invalid --shadowsocks target: {}", target))?;
+            let password = password.ok_or_else(|| {
+                anyhow!("--shadowsocks-password is required when using --shadowsocks <server:port>")
+            })?;
+            (addr.ip().to_string(), addr.port(), password)
+        };
+
+        Ok(Self {
+            remote_host,
+            remote_port,
+            // chacha20-ietf-poly1305: per-connection subkey is derived from the master
+            // key plus a random salt via HKDF-SHA1, each 2-byte length-prefixed chunk is
+            // then AEAD-encrypted with its own nonce. sslocal performs this, we just
+            // configure the cipher/password it uses.
+            cipher: cipher.unwrap_or_else(|| String::from("chacha20-ietf-poly1305")),
+            password,
+            forward_host,
+            forward_port,
+        })
+    }
+}
+
+// Picks a free loopback TCP port for the local Shadowsocks client to listen on
+// (OpenVPN's `--protocol tunnel` mode).
+fn free_local_port() -> anyhow::Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+// Picks a free loopback UDP port (WireGuard's `-u` relay mode) - TCP and UDP ports are
+// independent kernel namespaces, so a TCP-bound port number is no guarantee the same
+// number is free for UDP.
+fn free_local_udp_port() -> anyhow::Result<u16> {
+    let socket = UdpSocket::bind("127.0.0.1:0")?;
+    Ok(socket.local_addr()?.port())
+}
+
+fn pid_path(ns_name: &str) -> anyhow::Result<PathBuf> {
+    let mut path = config_dir()?;
+    path.push(format!("vopono/sslocal/{}.pid", ns_name));
+    Ok(path)
+}
+
+// Persists sslocal's pid next to the namespace's lockfile, the same way `Hooks::save`
+// persists hook scripts, so a later `vopono` invocation tearing the namespace down
+// (which never saw this one's `--shadowsocks` flag) can still kill it.
+fn save_pid(ns_name: &str, pid: u32) -> anyhow::Result<()> {
+    let path = pid_path(ns_name)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, pid.to_string())?;
+    Ok(())
+}
+
+// Kills the namespace's sslocal process (if any) and removes its pid file. Safe to call
+// even if `--shadowsocks` was never used for this namespace.
+pub fn kill_local(ns_name: &str) -> anyhow::Result<()> {
+    let path = pid_path(ns_name)?;
+    if let Ok(pid) = fs::read_to_string(&path) {
+        let _ = Command::new("kill").arg(pid.trim()).status();
+    }
+    let _ = fs::remove_file(&path);
+    Ok(())
+}
+
+// Launches `sslocal` (shadowsocks-rust's local client) inside the given network
+// namespace in `tunnel` mode, bound to loopback and fixed to forward to
+// `config.forward_host:forward_port` (the real VPN endpoint), so that
+// `run_openvpn`/`run_wireguard` can dial the local port instead of the VPN server
+// directly. `udp` must be set for WireGuard, since Shadowsocks' UDP relay (not its TCP
+// stream mode) is what can carry WireGuard's UDP datagrams. Must be called before the
+// VPN client is started. Its pid is persisted via `save_pid` so namespace teardown can
+// kill it later (mirrors `spawn_forwarder`'s same need in `websocket.rs`); returns the
+// handle only so the caller can report spawn failures, plus the local port the VPN
+// config should be rewritten to target.
+pub fn run_local(ns_name: &str, config: &ShadowsocksConfig, udp: bool) -> anyhow::Result<(Child, u16)> {
+    let local_port = if udp {
+        free_local_udp_port()?
+    } else {
+        free_local_port()?
+    };
+    info!(
+        "Starting Shadowsocks local client in namespace {} -> {}:{} (local port {}, forwarding to {}:{}, udp={})",
+        ns_name, config.remote_host, config.remote_port, local_port, config.forward_host, config.forward_port, udp
+    );
+
+    let mut cmd = Command::new("ip");
+    cmd.args(["netns", "exec", ns_name]).arg("sslocal");
+    cmd.args(["--local-addr", &format!("127.0.0.1:{}", local_port)]);
+    cmd.args([
+        "--server-addr",
+        &format!("{}:{}", config.remote_host, config.remote_port),
+    ]);
+    cmd.args(["--encrypt-method", &config.cipher]);
+    cmd.args(["--password", &config.password]);
+    cmd.args(["--protocol", "tunnel"]);
+    cmd.args([
+        "--forward-addr",
+        &format!("{}:{}", config.forward_host, config.forward_port),
+    ]);
+    if udp {
+        cmd.arg("-u");
+    }
+
+    let handle = cmd
+        .spawn()
+        .with_context(|| "Failed to launch sslocal - is shadowsocks-rust installed?")?;
+
+    debug!("sslocal running with pid: {}", handle.id());
+    save_pid(ns_name, handle.id())?;
+    Ok((handle, local_port))
+}
@@ -0,0 +1,77 @@
+use crate::args::SynchCommand;
+use crate::protonvpn;
+use crate::shadowsocks::sync_mullvad_bridges;
+use crate::util::config_dir;
+use crate::vpn::VpnProvider;
+use anyhow::Context;
+use log::info;
+use std::fs;
+
+// Syncs a provider's config dir so that `vopono exec` can find servers via
+// find_host_from_alias, or every provider in turn when none is given. Only ProtonVPN's
+// serverlist.csv and WireGuard configs are downloaded here; for Mullvad only the
+// Shadowsocks bridge relays are fetched, and the other providers are skipped with a log
+// message. Called directly for an explicit `vopono sync`.
+pub fn synch(command: SynchCommand) -> anyhow::Result<()> {
+    synch_impl(command, true)
+}
+
+// Same as `synch`, but for `exec`'s own "config dir missing, sync before continuing"
+// check: since Mullvad OpenVPN/WireGuard configs aren't implemented in this tree, that
+// directory never gets created, so every `vopono exec --provider mullvad` invocation
+// would otherwise hit this check - refreshing Shadowsocks bridge relays over the network
+// on every single run regardless of whether --shadowsocks is even in use. Only do that
+// live fetch for an explicit `vopono sync`.
+pub fn synch_if_missing(command: SynchCommand) -> anyhow::Result<()> {
+    synch_impl(command, false)
+}
+
+fn synch_impl(command: SynchCommand, explicit: bool) -> anyhow::Result<()> {
+    let provider = match command.vpn_provider {
+        Some(provider) => provider,
+        None => {
+            // No provider given (e.g. `vopono init`): sync every provider in turn.
+            for provider in &[
+                VpnProvider::PrivateInternetAccess,
+                VpnProvider::Mullvad,
+                VpnProvider::TigerVpn,
+                VpnProvider::ProtonVpn,
+            ] {
+                let provider_command = SynchCommand {
+                    vpn_provider: Some(provider.clone()),
+                    protocol: command.protocol.clone(),
+                    port: command.port,
+                };
+                synch_impl(provider_command, explicit)?;
+            }
+            return Ok(());
+        }
+    };
+
+    let mut provider_dir = config_dir()?;
+    provider_dir.push(format!("vopono/{}", provider.alias()));
+    fs::create_dir_all(&provider_dir)
+        .with_context(|| format!("Could not create config dir: {}", provider_dir.display()))?;
+
+    match provider {
+        VpnProvider::ProtonVpn => protonvpn::synch(&provider_dir)?,
+        VpnProvider::Mullvad => {
+            info!(
+                "Synch for {} OpenVPN/WireGuard configs is not implemented in this tree",
+                provider
+            );
+            if explicit {
+                sync_mullvad_bridges(&provider_dir)?;
+            }
+        }
+        VpnProvider::PrivateInternetAccess | VpnProvider::TigerVpn => {
+            info!(
+                "Synch for {} is not implemented in this tree, skipping",
+                provider
+            );
+        }
+        VpnProvider::Custom => {}
+    }
+
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use super::protonvpn::protonvpn_suffix;
 use super::util::config_dir;
 use anyhow::{anyhow, Context};
 use clap::arg_enum;
@@ -17,6 +18,7 @@ pub enum VpnProvider {
     PrivateInternetAccess,
     Mullvad,
     TigerVpn,
+    ProtonVpn,
     Custom,
 }
 }
@@ -27,6 +29,7 @@ impl VpnProvider {
             Self::PrivateInternetAccess => String::from("pia"),
             Self::Mullvad => String::from("mv"),
             Self::TigerVpn => String::from("tig"),
+            Self::ProtonVpn => String::from("pvpn"),
             Self::Custom => String::from("cus"),
         }
     }
@@ -39,6 +42,7 @@ impl VpnProvider {
             ],
             Self::Mullvad => vec![IpAddr::from_str("193.138.218.74")],
             Self::TigerVpn => vec![IpAddr::from_str("8.8.8.8"), IpAddr::from_str("8.8.4.4")],
+            Self::ProtonVpn => vec![IpAddr::from_str("10.2.0.1")],
             Self::Custom => vec![IpAddr::from_str("8.8.8.8"), IpAddr::from_str("8.8.4.4")],
         };
 
@@ -85,11 +89,26 @@ pub enum Protocol {
 }
 }
 
-// pub enum Firewall {
-//     IpTables,
-//     NfTables,
-//     Ufw,
-// }
+arg_enum! {
+    #[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+pub enum Firewall {
+    IpTables,
+    NfTables,
+}
+}
+
+impl Firewall {
+    // Picks nftables when available (and no explicit choice was given) to avoid the
+    // mixed iptables/nftables state that legacy iptables can leave behind on modern
+    // distros that route everything through nftables by default.
+    pub fn get_firewall(choice: Option<Self>) -> Self {
+        match choice {
+            Some(firewall) => firewall,
+            None if super::nftables::is_available() => Self::NfTables,
+            None => Self::IpTables,
+        }
+    }
+}
 
 #[derive(Serialize, Deserialize)]
 pub struct VpnServer {
@@ -201,6 +220,9 @@ pub fn get_auth(provider: &VpnProvider) -> anyhow::Result<()> {
                     "OpenVPN username (see https://www.tigervpn.com/dashboard/geeks )"
                 }
                 VpnProvider::PrivateInternetAccess => "PrivateInternetAccess username",
+                VpnProvider::ProtonVpn => {
+                    "ProtonVPN OpenVPN username (see https://account.protonvpn.com/account, distinct from your login)"
+                }
                 VpnProvider::Custom => "OpenVPN username",
             };
             let mut username = Input::<String>::new().with_prompt(user_prompt).interact()?;
@@ -214,6 +236,10 @@ pub fn get_auth(provider: &VpnProvider) -> anyhow::Result<()> {
                 }
             }
 
+            if *provider == VpnProvider::ProtonVpn {
+                username.push_str(&protonvpn_suffix()?);
+            }
+
             let password = if *provider == VpnProvider::Mullvad {
                 String::from("m")
             } else {
@@ -251,6 +277,7 @@ pub fn get_protocol(
                     "Wireguard not implemented for PrivateInternetAccess"
                 ))
             }
+            VpnProvider::ProtonVpn => Ok(Protocol::Wireguard),
             VpnProvider::Custom => Ok(Protocol::Wireguard),
         },
         Some(Protocol::OpenVpn) => Ok(Protocol::OpenVpn),
@@ -258,6 +285,15 @@ pub fn get_protocol(
             VpnProvider::Mullvad => Ok(Protocol::Wireguard),
             VpnProvider::TigerVpn => Ok(Protocol::OpenVpn),
             VpnProvider::PrivateInternetAccess => Ok(Protocol::OpenVpn),
+            // ProtonVPN exposes both WireGuard and OpenVPN configs per-server; prefer
+            // WireGuard if we have already synced a config for it, else fall back.
+            VpnProvider::ProtonVpn => {
+                if super::protonvpn::has_synced_wireguard_configs()? {
+                    Ok(Protocol::Wireguard)
+                } else {
+                    Ok(Protocol::OpenVpn)
+                }
+            }
             VpnProvider::Custom => Ok(Protocol::Wireguard),
         },
     }
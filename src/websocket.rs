@@ -0,0 +1,400 @@
+use crate::util::config_dir;
+use anyhow::{bail, Context};
+use log::{debug, info, warn};
+use rand::Rng;
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+use std::thread;
+use url::Url;
+
+// Inspired by VPNCloud's websocket proxy mode: smuggles VPN traffic as binary
+// WebSocket frames over what looks like an ordinary HTTPS connection on port 443, for
+// networks that block raw UDP/TCP VPN ports but allow outbound HTTPS.
+#[derive(Debug, Clone)]
+pub struct WebSocketProxy {
+    pub url: Url,
+}
+
+impl FromStr for WebSocketProxy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let url = Url::parse(s).with_context(|| format!("Invalid websocket proxy URL: {}", s))?;
+        match url.scheme() {
+            "ws" | "wss" => {}
+            other => bail!("Unsupported websocket-proxy scheme: {} (expected ws:// or wss://)", other),
+        }
+        Ok(Self { url })
+    }
+}
+
+impl WebSocketProxy {
+    pub fn host(&self) -> anyhow::Result<String> {
+        self.url
+            .host_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("websocket-proxy URL has no host: {}", self.url))
+    }
+
+    pub fn port(&self) -> u16 {
+        self.url
+            .port_or_known_default()
+            .unwrap_or(if self.url.scheme() == "wss" { 443 } else { 80 })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.url.scheme() == "wss"
+    }
+
+    fn path(&self) -> String {
+        let path = self.url.path();
+        match self.url.query() {
+            Some(q) => format!("{}?{}", path, q),
+            None => path.to_string(),
+        }
+    }
+}
+
+fn free_local_port() -> anyhow::Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+// Either a plain or TLS-wrapped TCP connection to the remote proxy endpoint, so the
+// handshake/frame relay code below does not need to care which.
+enum RemoteStream {
+    Plain(TcpStream),
+    Tls(Box<native_tls::TlsStream<TcpStream>>),
+}
+
+impl Read for RemoteStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(s) => s.read(buf),
+            Self::Tls(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for RemoteStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Plain(s) => s.write(buf),
+            Self::Tls(s) => s.write(buf),
+        }
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Self::Plain(s) => s.flush(),
+            Self::Tls(s) => s.flush(),
+        }
+    }
+}
+
+fn connect_remote(proxy: &WebSocketProxy) -> anyhow::Result<RemoteStream> {
+    let host = proxy.host()?;
+    let port = proxy.port();
+    let tcp = TcpStream::connect((host.as_str(), port))
+        .with_context(|| format!("Could not connect to websocket proxy {}:{}", host, port))?;
+    if proxy.is_tls() {
+        let connector = native_tls::TlsConnector::new()?;
+        let tls = connector
+            .connect(&host, tcp)
+            .with_context(|| format!("TLS handshake with {} failed", host))?;
+        Ok(RemoteStream::Tls(Box::new(tls)))
+    } else {
+        Ok(RemoteStream::Plain(tcp))
+    }
+}
+
+// Performs the client-side HTTP Upgrade handshake (RFC 6455 section 4.1): sends a GET
+// with the websocket upgrade headers and a random Sec-WebSocket-Key, then checks the
+// server replies 101 Switching Protocols with the matching Sec-WebSocket-Accept.
+fn ws_handshake(stream: &mut RemoteStream, proxy: &WebSocketProxy) -> anyhow::Result<()> {
+    let host = proxy.host()?;
+    let key_bytes: [u8; 16] = rand::thread_rng().gen();
+    let key = base64::encode(key_bytes);
+
+    let request = format!(
+        "GET {} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Upgrade: websocket\r\n\
+         Connection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\n\
+         Sec-WebSocket-Version: 13\r\n\
+         \r\n",
+        proxy.path(),
+        host,
+        key,
+    );
+    stream.write_all(request.as_bytes())?;
+    stream.flush()?;
+
+    let mut reader = BufReader::new(&mut *stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line)?;
+    if !status_line.contains("101") {
+        bail!("websocket-proxy handshake failed: {}", status_line.trim());
+    }
+
+    let mut accept = None;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-accept") {
+                accept = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let expected = {
+        let mut hasher = Sha1::new();
+        hasher.update(key.as_bytes());
+        hasher.update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+        base64::encode(hasher.finalize())
+    };
+    if accept.as_deref() != Some(expected.as_str()) {
+        bail!("websocket-proxy handshake failed: unexpected Sec-WebSocket-Accept");
+    }
+    Ok(())
+}
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+// Writes a single-frame WebSocket message. Frames from the client MUST be masked
+// (RFC 6455 section 5.1); frames we send back in response to a ping are too, since
+// we are always the client side of this connection.
+fn write_ws_frame(stream: &mut impl Write, opcode: u8, payload: &[u8]) -> anyhow::Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 14);
+    frame.push(0x80 | opcode); // FIN + opcode
+
+    let mask_key: [u8; 4] = rand::thread_rng().gen();
+    let len = payload.len();
+    if len < 126 {
+        frame.push(0x80 | len as u8);
+    } else if len <= u16::MAX as usize {
+        frame.push(0x80 | 126);
+        frame.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        frame.push(0x80 | 127);
+        frame.extend_from_slice(&(len as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(&mask_key);
+    for (i, byte) in payload.iter().enumerate() {
+        frame.push(byte ^ mask_key[i % 4]);
+    }
+
+    stream.write_all(&frame)?;
+    stream.flush()?;
+    Ok(())
+}
+
+// Reads a single WebSocket frame (not reassembling fragmented messages - VPN packets
+// are small enough that peers send them as one frame). Returns the opcode and the
+// unmasked payload.
+fn read_ws_frame(stream: &mut impl Read) -> anyhow::Result<(u8, Vec<u8>)> {
+    let mut header = [0u8; 2];
+    stream.read_exact(&mut header)?;
+    let opcode = header[0] & 0x0F;
+    let masked = header[1] & 0x80 != 0;
+    let mut len = (header[1] & 0x7F) as u64;
+
+    if len == 126 {
+        let mut ext = [0u8; 2];
+        stream.read_exact(&mut ext)?;
+        len = u16::from_be_bytes(ext) as u64;
+    } else if len == 127 {
+        let mut ext = [0u8; 8];
+        stream.read_exact(&mut ext)?;
+        len = u64::from_be_bytes(ext);
+    }
+
+    let mask_key = if masked {
+        let mut key = [0u8; 4];
+        stream.read_exact(&mut key)?;
+        Some(key)
+    } else {
+        None
+    };
+
+    let mut payload = vec![0u8; len as usize];
+    stream.read_exact(&mut payload)?;
+    if let Some(key) = mask_key {
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= key[i % 4];
+        }
+    }
+    Ok((opcode, payload))
+}
+
+// Relays one accepted local connection to/from the remote proxy until either side
+// closes or errors, answering pings with pongs to keep the connection alive.
+fn relay(local: TcpStream, mut remote: RemoteStream) -> anyhow::Result<()> {
+    let mut remote_writer = remote_writer_handle(&remote)?;
+    let mut local_reader = local.try_clone()?;
+    let mut local_writer = local;
+
+    let uplink = thread::spawn(move || -> anyhow::Result<()> {
+        let mut buf = [0u8; 16 * 1024];
+        loop {
+            let n = local_reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            write_ws_frame(&mut remote_writer, OPCODE_BINARY, &buf[..n])?;
+        }
+        Ok(())
+    });
+
+    loop {
+        let (opcode, payload) = match read_ws_frame(&mut remote) {
+            Ok(frame) => frame,
+            Err(e) => {
+                debug!("websocket-proxy remote connection closed: {}", e);
+                break;
+            }
+        };
+        match opcode {
+            OPCODE_BINARY | OPCODE_CONTINUATION => {
+                local_writer.write_all(&payload)?;
+            }
+            OPCODE_TEXT => {
+                warn!("websocket-proxy: ignoring unexpected text frame");
+            }
+            OPCODE_PING => {
+                write_ws_frame(&mut remote, OPCODE_PONG, &payload)?;
+            }
+            OPCODE_PONG => {}
+            OPCODE_CLOSE => break,
+            _ => {}
+        }
+    }
+
+    let _ = uplink.join();
+    Ok(())
+}
+
+// write_ws_frame() only needs Write, but the uplink thread must own a writable handle
+// to the remote connection independent of the read loop above (which needs &mut
+// remote for read_ws_frame). A plain TCP stream supports this via try_clone(), same as
+// the local TcpStream split above; a TLS stream has no such second handle, so this
+// returns an error for it and wss:// connections are not relayed.
+fn remote_writer_handle(remote: &RemoteStream) -> anyhow::Result<RemoteStream> {
+    match remote {
+        RemoteStream::Plain(s) => Ok(RemoteStream::Plain(s.try_clone()?)),
+        RemoteStream::Tls(_) => {
+            bail!("websocket-proxy: wss:// uplink writer handle is not supported in this build")
+        }
+    }
+}
+
+fn pid_path(ns_name: &str) -> anyhow::Result<PathBuf> {
+    let mut path = config_dir()?;
+    path.push(format!("vopono/ws_forward/{}.pid", ns_name));
+    Ok(path)
+}
+
+// Persists the forwarder's pid next to the namespace's lockfile, the same way
+// `Hooks::save` persists hook scripts, so a later `vopono` invocation tearing the
+// namespace down (which never saw this one's `--websocket-proxy` flag) can still kill it.
+fn save_pid(ns_name: &str, pid: u32) -> anyhow::Result<()> {
+    let path = pid_path(ns_name)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, pid.to_string())?;
+    Ok(())
+}
+
+// Kills the namespace's WebSocket forwarder (if any) and removes its pid file. Safe to
+// call even if `--websocket-proxy` was never used for this namespace.
+pub fn kill_forwarder(ns_name: &str) -> anyhow::Result<()> {
+    let path = pid_path(ns_name)?;
+    if let Ok(pid) = fs::read_to_string(&path) {
+        let _ = Command::new("kill").arg(pid.trim()).status();
+    }
+    let _ = fs::remove_file(&path);
+    Ok(())
+}
+
+// Starts a small local TCP<->WebSocket forwarder inside the network namespace. It
+// accepts the VPN client's TCP connection(s) on a loopback port, performs the HTTP
+// Upgrade handshake (with TLS for `wss://`), and relays each packet as a binary
+// WebSocket frame to/from the remote proxy endpoint, answering ping frames with pong to
+// keep the connection alive. Only `ws://` connections are relayed: for `wss://` the
+// connection ends after the handshake (see `remote_writer_handle`).
+//
+// The accept loop itself (`run_forwarder`) is launched as a detached `ip netns exec`
+// child running this same binary's hidden `ws-forward` subcommand, rather than as a
+// thread of the calling process: `vopono exec` only waits on the wrapped application
+// and then exits, so an in-process thread would die with it, while a namespace (and the
+// forwarder it needs) can outlive the `vopono exec` invocation that created it - exactly
+// the problem `Hooks::save`/`Hooks::load` solve for hook scripts. The child's pid is
+// persisted so namespace teardown can kill it later. Returns the loopback port OpenVPN
+// should be pointed at via `proto tcp`.
+pub fn spawn_forwarder(ns_name: &str, proxy: &WebSocketProxy) -> anyhow::Result<u16> {
+    let local_port = free_local_port()?;
+    info!(
+        "Starting WebSocket forwarder in namespace {}: 127.0.0.1:{} <-> {} (tls={})",
+        ns_name,
+        local_port,
+        proxy.url,
+        proxy.is_tls()
+    );
+
+    let exe = std::env::current_exe().with_context(|| "Could not resolve vopono's own path")?;
+    let child = Command::new("ip")
+        .args(["netns", "exec", ns_name])
+        .arg(exe)
+        .args(["ws-forward", &local_port.to_string(), proxy.url.as_str()])
+        .spawn()
+        .with_context(|| "Failed to launch websocket-proxy forwarder")?;
+    save_pid(ns_name, child.id())?;
+
+    Ok(local_port)
+}
+
+// Blocking accept loop run by the hidden `vopono ws-forward` subcommand: never returns
+// under normal operation, spawning a thread per accepted connection to relay it to the
+// remote proxy.
+pub fn run_forwarder(local_port: u16, proxy: &WebSocketProxy) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", local_port)).with_context(|| {
+        format!("Could not bind websocket-proxy forwarder to port {}", local_port)
+    })?;
+
+    for conn in listener.incoming() {
+        let local = match conn {
+            Ok(local) => local,
+            Err(e) => {
+                warn!("websocket-proxy: accept() failed: {}", e);
+                continue;
+            }
+        };
+        let proxy = proxy.clone();
+        thread::spawn(move || {
+            let result = (|| -> anyhow::Result<()> {
+                let mut remote = connect_remote(&proxy)?;
+                ws_handshake(&mut remote, &proxy)?;
+                relay(local, remote)
+            })();
+            if let Err(e) = result {
+                debug!("websocket-proxy connection ended: {}", e);
+            }
+        });
+    }
+    Ok(())
+}